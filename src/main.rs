@@ -1,14 +1,18 @@
 use std::fs;
+use subtle::ConstantTimeEq;
 use axum::body::{Body, Empty, Full};
-use axum::extract::{Path, State};
-use axum::http::{header, HeaderValue, Request};
+use futures::TryStreamExt;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::{ReaderStream, StreamReader};
+use axum::extract::{BodyStream, ConnectInfo, Path, State};
+use axum::http::{header, HeaderMap, HeaderValue, Request, Uri};
 use axum::middleware::Next;
 use axum::{
     body,
     http::StatusCode,
     middleware,
     response::{IntoResponse, Response},
-    routing::get,
+    routing::{get, post},
     Router,
 };
 use clap::Parser;
@@ -16,15 +20,7 @@ use std::net::SocketAddr;
 use std::sync::{Arc};
 use time::{macros::format_description, UtcOffset};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, fmt::time::OffsetTime, fmt};
-use serde::{Serialize, Deserialize};
-
-#[derive(Serialize, Deserialize, Debug)]
-struct AccessLog {
-    uri: String,
-    method: String,
-    req_body: String,
-    status_code: u16
-}
+use serde::Serialize;
 
 #[derive(Parser)]
 struct AppSetting {
@@ -32,57 +28,687 @@ struct AppSetting {
     static_dir: String,
 
     #[arg(short, long, default_value_t = 3000)]
-    port: u16
+    port: u16,
+
+    /// Serve a directory listing for directories with no `index.html`.
+    #[arg(long)]
+    autoindex: bool,
+
+    /// Bearer token required to call `POST /_deploy`. When unset, the
+    /// endpoint refuses every request.
+    #[arg(long)]
+    deploy_token: Option<String>,
+
+    /// Access-log template. Supports `$remote_addr`, `$request_method`,
+    /// `$uri`, `$status`, `$http_user_agent`, `$request_time` and
+    /// `$body_bytes_sent`. An empty string disables access logging.
+    #[arg(
+        long,
+        default_value = "$remote_addr \"$request_method $uri\" $status $body_bytes_sent \"$http_user_agent\" $request_time"
+    )]
+    log_format: String,
+
+    /// Canonicalized `static_dir`, resolved once at startup so every request
+    /// only has to canonicalize the (attacker-controlled) target path.
+    #[arg(skip)]
+    static_root: std::path::PathBuf,
+}
+
+/// Parses an `Accept-Encoding` header into the codings the client will accept,
+/// ordered from most to least preferred according to their `q` value.
+fn parse_accept_encoding(value: &str) -> Vec<String> {
+    let mut codings: Vec<(String, f32)> = value
+        .split(',')
+        .filter_map(|part| {
+            let mut segments = part.trim().split(';');
+            let name = segments.next()?.trim().to_lowercase();
+            if name.is_empty() {
+                return None;
+            }
+            let quality = segments
+                .find_map(|seg| seg.trim().strip_prefix("q="))
+                .and_then(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            (quality > 0.0).then_some((name, quality))
+        })
+        .collect();
+    codings.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    codings.into_iter().map(|(name, _)| name).collect()
+}
+
+/// Appends an extra extension to a path, e.g. `app.js` + `br` -> `app.js.br`.
+fn with_extra_extension(path: &std::path::Path, ext: &str) -> std::path::PathBuf {
+    let mut os = path.as_os_str().to_owned();
+    os.push(".");
+    os.push(ext);
+    std::path::PathBuf::from(os)
+}
+
+/// Canonicalizes `candidate` and returns it only if it still resolves inside
+/// `static_root` — a sibling file can be a symlink, so `.exists()` alone
+/// isn't enough to rule out escaping the root the way a plain file lookup
+/// would have already been checked by `resolve_within_root`.
+fn canonicalize_within_root(
+    static_root: &std::path::Path,
+    candidate: &std::path::Path,
+) -> Option<std::path::PathBuf> {
+    let resolved = fs::canonicalize(candidate).ok()?;
+    resolved.starts_with(static_root).then_some(resolved)
 }
 
-async fn static_path_handler(Path(path): Path<String>, State(app_setting): State<Arc<AppSetting>>) -> impl IntoResponse {
-    let mut path = path.trim_start_matches('/');
-    if path.is_empty() {
-        path = "index.html";
+/// Looks for a precompressed sibling of `file_path` that the client accepts,
+/// preferring brotli over gzip when both are available and acceptable.
+fn negotiate_precompressed(
+    static_root: &std::path::Path,
+    file_path: &std::path::Path,
+    accept_encoding: &str,
+) -> Option<(&'static str, std::path::PathBuf)> {
+    let accepted = parse_accept_encoding(accept_encoding);
+    let accepts = |coding: &str| accepted.iter().any(|c| c == coding || c == "*");
+
+    if accepts("br") {
+        let br_path = with_extra_extension(file_path, "br");
+        if let Some(resolved) = canonicalize_within_root(static_root, &br_path) {
+            return Some(("br", resolved));
+        }
+    }
+    if accepts("gzip") {
+        let gz_path = with_extra_extension(file_path, "gz");
+        if let Some(resolved) = canonicalize_within_root(static_root, &gz_path) {
+            return Some(("gzip", resolved));
+        }
     }
+    None
+}
 
-    let mime_type = mime_guess::from_path(path).first_or_text_plain();
-    let static_dir = std::path::Path::new(&app_setting.static_dir);
-    let file_path = static_dir.join(path);
+/// Computes a weak ETag from a file's size and modification time.
+fn weak_etag(metadata: &fs::Metadata) -> String {
+    let mtime_secs = metadata
+        .modified()
+        .ok()
+        .and_then(|m| m.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    format!("W/\"{:x}-{:x}\"", metadata.len(), mtime_secs)
+}
 
-    match file_path.exists() {
-        false => Response::builder()
-            .status(StatusCode::NOT_FOUND)
+/// Result of resolving a `Range` header against the representation's length.
+enum RangeResult {
+    /// No `Range` header, or one that doesn't apply (e.g. an unrecognised unit).
+    Full,
+    /// A satisfiable inclusive byte range.
+    Satisfiable(u64, u64),
+    /// A syntactically valid range outside the representation's bounds.
+    Unsatisfiable,
+}
+
+/// Parses a single `bytes=start-end` / `bytes=-suffix` / `bytes=start-` range.
+/// Only the first range of a (possibly multi-range) header is honored.
+fn parse_range(value: &str, total_len: u64) -> RangeResult {
+    let Some(spec) = value.strip_prefix("bytes=") else {
+        return RangeResult::Full;
+    };
+    let Some(spec) = spec.split(',').next() else {
+        return RangeResult::Full;
+    };
+    let spec = spec.trim();
+    let Some((start_str, end_str)) = spec.split_once('-') else {
+        return RangeResult::Full;
+    };
+
+    if start_str.is_empty() {
+        let Ok(suffix_len) = end_str.parse::<u64>() else {
+            return RangeResult::Full;
+        };
+        if suffix_len == 0 || total_len == 0 {
+            return RangeResult::Unsatisfiable;
+        }
+        let start = total_len.saturating_sub(suffix_len);
+        return RangeResult::Satisfiable(start, total_len - 1);
+    }
+
+    let Ok(start) = start_str.parse::<u64>() else {
+        return RangeResult::Full;
+    };
+    let end = if end_str.is_empty() {
+        total_len.saturating_sub(1)
+    } else {
+        match end_str.parse::<u64>() {
+            Ok(end) => end.min(total_len.saturating_sub(1)),
+            Err(_) => return RangeResult::Full,
+        }
+    };
+
+    if total_len == 0 || start > end || start >= total_len {
+        RangeResult::Unsatisfiable
+    } else {
+        RangeResult::Satisfiable(start, end)
+    }
+}
+
+/// `true` when `If-Range` names the current representation, so a `Range`
+/// request may be honored rather than falling back to the full body.
+fn if_range_matches(headers: &HeaderMap, etag: &str, last_modified: &str) -> bool {
+    match headers.get(header::IF_RANGE).and_then(|v| v.to_str().ok()) {
+        Some(value) => value == etag || value == last_modified,
+        None => true,
+    }
+}
+
+/// Opens `path` and streams `len` bytes starting at `start` in bounded
+/// chunks, instead of buffering the whole file in memory.
+async fn stream_file_range(path: &std::path::Path, start: u64, len: u64) -> std::io::Result<Body> {
+    let mut file = tokio::fs::File::open(path).await?;
+    if start > 0 {
+        file.seek(std::io::SeekFrom::Start(start)).await?;
+    }
+    let stream = ReaderStream::new(file.take(len));
+    Ok(Body::wrap_stream(stream))
+}
+
+/// Builds an empty response with an explicit zero `Content-Length`, so the
+/// access-log middleware (which reads response headers before hyper would
+/// otherwise fill this one in) can report an accurate `$body_bytes_sent`.
+fn empty_response(status: StatusCode) -> Response {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_LENGTH, 0)
+        .body(body::boxed(Empty::new()))
+        .unwrap()
+}
+
+fn internal_server_error() -> Response {
+    empty_response(StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+/// `true` when the cached representation named by `If-None-Match` /
+/// `If-Modified-Since` is still current, i.e. the request should get a 304.
+fn is_not_modified(headers: &HeaderMap, etag: &str, last_modified: &httpdate::HttpDate) -> bool {
+    if let Some(value) = headers.get(header::IF_NONE_MATCH).and_then(|v| v.to_str().ok()) {
+        return value.split(',').any(|candidate| candidate.trim() == etag || candidate.trim() == "*");
+    }
+    if let Some(value) = headers.get(header::IF_MODIFIED_SINCE).and_then(|v| v.to_str().ok()) {
+        if let Ok(since) = value.parse::<httpdate::HttpDate>() {
+            return *last_modified <= since;
+        }
+    }
+    false
+}
+
+/// Outcome of resolving a request path against the canonicalized static root.
+enum Resolved {
+    File(std::path::PathBuf),
+    Directory(std::path::PathBuf),
+    NotFound,
+    Forbidden,
+}
+
+/// Resolves a (possibly percent-encoded, possibly traversal-laden) request
+/// path against the canonicalized static root, rejecting anything that
+/// escapes it.
+fn resolve_within_root(static_root: &std::path::Path, raw_path: &str) -> Resolved {
+    let decoded = percent_encoding::percent_decode_str(raw_path).decode_utf8_lossy();
+    let path = decoded.trim_start_matches('/');
+    let candidate = if path.is_empty() {
+        static_root.to_path_buf()
+    } else {
+        static_root.join(path)
+    };
+
+    match fs::canonicalize(&candidate) {
+        Err(_) => Resolved::NotFound,
+        Ok(resolved) if !resolved.starts_with(static_root) => Resolved::Forbidden,
+        Ok(resolved) if resolved.is_dir() => Resolved::Directory(resolved),
+        Ok(resolved) => Resolved::File(resolved),
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct DirEntryInfo {
+    name: String,
+    is_dir: bool,
+    size: u64,
+    mtime: String,
+}
+
+fn html_escape(input: &str) -> String {
+    input
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+fn render_autoindex_html(entries: &[DirEntryInfo]) -> String {
+    let mut rows = String::new();
+    for entry in entries {
+        let href = percent_encoding::utf8_percent_encode(&entry.name, percent_encoding::NON_ALPHANUMERIC);
+        let display_name = if entry.is_dir {
+            format!("{}/", entry.name)
+        } else {
+            entry.name.clone()
+        };
+        let size = if entry.is_dir { "-".to_string() } else { entry.size.to_string() };
+        rows.push_str(&format!(
+            "<tr><td><a href=\"{href}\">{name}</a></td><td>{size}</td><td>{mtime}</td></tr>\n",
+            href = href,
+            name = html_escape(&display_name),
+            size = size,
+            mtime = html_escape(&entry.mtime),
+        ));
+    }
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>Index</title></head>\n<body>\n<table>\n{}</table>\n</body>\n</html>\n",
+        rows
+    )
+}
+
+/// Renders a directory listing as HTML, or as JSON when the client's
+/// `Accept` header prefers `application/json`.
+async fn render_autoindex(dir: &std::path::Path, headers: &HeaderMap) -> Response {
+    let read_dir = match fs::read_dir(dir) {
+        Ok(read_dir) => read_dir,
+        Err(_) => return internal_server_error(),
+    };
+
+    let mut entries = Vec::new();
+    for entry in read_dir.filter_map(|e| e.ok()) {
+        let metadata = match entry.metadata() {
+            Ok(metadata) => metadata,
+            Err(_) => continue,
+        };
+        let mtime = metadata
+            .modified()
+            .map(httpdate::fmt_http_date)
+            .unwrap_or_default();
+        entries.push(DirEntryInfo {
+            name: entry.file_name().to_string_lossy().into_owned(),
+            is_dir: metadata.is_dir(),
+            size: metadata.len(),
+            mtime,
+        });
+    }
+    entries.sort_by(|a, b| {
+        b.is_dir
+            .cmp(&a.is_dir)
+            .then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase()))
+    });
+
+    let wants_json = headers
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .map(|accept| accept.contains("application/json"))
+        .unwrap_or(false);
+
+    if wants_json {
+        let json_bytes = serde_json::to_vec(&entries).unwrap();
+        Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(header::CONTENT_LENGTH, json_bytes.len())
+            // The representation depends on Accept, so a cache must
+            // revalidate rather than reuse one client's negotiated body for
+            // another client requesting the other format.
+            .header(header::VARY, "Accept")
+            .body(body::boxed(Full::from(json_bytes)))
+            .unwrap()
+    } else {
+        let html = render_autoindex_html(&entries);
+        Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, "text/html; charset=utf-8")
+            .header(header::CONTENT_LENGTH, html.len())
+            .header(header::VARY, "Accept")
+            .body(body::boxed(Full::from(html)))
+            .unwrap()
+    }
+}
+
+async fn static_path_handler(
+    Path(path): Path<String>,
+    State(app_setting): State<Arc<AppSetting>>,
+    headers: HeaderMap,
+    uri: Uri,
+) -> impl IntoResponse {
+    let file_path = match resolve_within_root(&app_setting.static_root, &path) {
+        Resolved::NotFound => return empty_response(StatusCode::NOT_FOUND),
+        Resolved::Forbidden => return empty_response(StatusCode::FORBIDDEN),
+        Resolved::File(file_path) => file_path,
+        Resolved::Directory(dir) => {
+            // Relative links in an index page (autoindex or index.html) resolve
+            // against the parent of the request URL unless it ends in `/`, so
+            // redirect bare directory requests to the slash-terminated form.
+            if !path.is_empty() && !path.ends_with('/') {
+                let mut location = format!("{}/", uri.path());
+                if let Some(query) = uri.query() {
+                    location.push('?');
+                    location.push_str(query);
+                }
+                return Response::builder()
+                    .status(StatusCode::PERMANENT_REDIRECT)
+                    .header(header::LOCATION, location)
+                    .header(header::CONTENT_LENGTH, 0)
+                    .body(body::boxed(Empty::new()))
+                    .unwrap();
+            }
+
+            let index = dir.join("index.html");
+            if index.is_file() {
+                index
+            } else if app_setting.autoindex {
+                return render_autoindex(&dir, &headers).await;
+            } else {
+                return empty_response(StatusCode::NOT_FOUND);
+            }
+        }
+    };
+    let mime_type = mime_guess::from_path(&file_path).first_or_text_plain();
+
+    let accept_encoding = headers
+        .get(header::ACCEPT_ENCODING)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| negotiate_precompressed(&app_setting.static_root, &file_path, v));
+
+    let (content_encoding, served_path) = match accept_encoding {
+        Some((encoding, path)) => (Some(encoding), path),
+        None => (None, file_path),
+    };
+
+    let metadata = match tokio::fs::metadata(&served_path).await {
+        Ok(metadata) => metadata,
+        Err(_) => return internal_server_error(),
+    };
+    let etag = weak_etag(&metadata);
+    let last_modified_time = metadata.modified().unwrap_or(std::time::SystemTime::UNIX_EPOCH);
+    let last_modified = httpdate::fmt_http_date(last_modified_time);
+    let last_modified_parsed: httpdate::HttpDate = last_modified_time.into();
+
+    if is_not_modified(&headers, &etag, &last_modified_parsed) {
+        return Response::builder()
+            .status(StatusCode::NOT_MODIFIED)
+            .header(header::ETAG, &etag)
+            .header(header::LAST_MODIFIED, &last_modified)
+            .header(header::VARY, "Accept-Encoding")
+            .header(header::CONTENT_LENGTH, 0)
+            .body(body::boxed(Empty::new()))
+            .unwrap();
+    }
+
+    let total_len = metadata.len();
+    let range = headers
+        .get(header::RANGE)
+        .and_then(|v| v.to_str().ok())
+        .filter(|_| if_range_matches(&headers, &etag, &last_modified))
+        .map(|v| parse_range(v, total_len))
+        .unwrap_or(RangeResult::Full);
+
+    if let RangeResult::Unsatisfiable = range {
+        return Response::builder()
+            .status(StatusCode::RANGE_NOT_SATISFIABLE)
+            .header(header::CONTENT_RANGE, format!("bytes */{}", total_len))
+            .header(header::ACCEPT_RANGES, "bytes")
+            .header(header::CONTENT_LENGTH, 0)
             .body(body::boxed(Empty::new()))
-            .unwrap(),
-        true => {
-            let file_content = fs::read(file_path).unwrap();
-            Response::builder()
-                .status(StatusCode::OK)
-                .header(
-                    header::CONTENT_TYPE,
-                    HeaderValue::from_str(mime_type.as_ref()).unwrap(),
-                )
-                .body(body::boxed(Full::from(file_content)))
-                .unwrap()
+            .unwrap();
+    }
+
+    let (status, start, len, content_range) = match range {
+        RangeResult::Satisfiable(start, end) => (
+            StatusCode::PARTIAL_CONTENT,
+            start,
+            end - start + 1,
+            Some(format!("bytes {}-{}/{}", start, end, total_len)),
+        ),
+        _ => (StatusCode::OK, 0, total_len, None),
+    };
+
+    let stream_body = match stream_file_range(&served_path, start, len).await {
+        Ok(body) => body,
+        Err(_) => return internal_server_error(),
+    };
+
+    let mut builder = Response::builder()
+        .status(status)
+        .header(
+            header::CONTENT_TYPE,
+            HeaderValue::from_str(mime_type.as_ref()).unwrap(),
+        )
+        .header(header::CONTENT_LENGTH, len)
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header(header::ETAG, &etag)
+        .header(header::LAST_MODIFIED, &last_modified)
+        // Whether a precompressed sibling was served depends on
+        // Accept-Encoding even on requests where none was found, so this has
+        // to be unconditional — otherwise a shared cache could serve one
+        // client's negotiated encoding to another with a different
+        // Accept-Encoding.
+        .header(header::VARY, "Accept-Encoding");
+    if let Some(content_range) = content_range {
+        builder = builder.header(header::CONTENT_RANGE, content_range);
+    }
+    if let Some(content_encoding) = content_encoding {
+        builder = builder.header(header::CONTENT_ENCODING, content_encoding);
+    }
+    builder.body(body::boxed(stream_body)).unwrap()
+}
+
+/// Extracts a tar archive into the target directory, checking each entry
+/// stays within it just before unpacking that entry. When an entry is
+/// rejected, the entries before it have already been unpacked.
+fn extract_tar_gz(archive_path: &std::path::Path, target_dir: &std::path::Path) -> std::io::Result<()> {
+    let file = fs::File::open(archive_path)?;
+    let decoder = flate2::read::GzDecoder::new(file);
+    let mut archive = tar::Archive::new(decoder);
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let entry_path = entry.path()?.into_owned();
+
+        // GNU tar conventionally prefixes entries with `./` (e.g. from
+        // `tar czf x.tar.gz -C dist .`), so ignore `CurDir` components before
+        // requiring the rest to be a plain relative run of `Normal`
+        // components. This still rejects `..` escapes as well as absolute
+        // paths (`RootDir`/`Prefix`) rather than relying on `unpack_in`'s
+        // undocumented handling of them.
+        let mut components = entry_path
+            .components()
+            .filter(|component| !matches!(component, std::path::Component::CurDir))
+            .peekable();
+        if components.peek().is_none() {
+            // A bare `.` entry (the directory itself) has nothing to extract.
+            continue;
+        }
+        let is_plain_relative = components.all(|component| matches!(component, std::path::Component::Normal(_)));
+        if !is_plain_relative {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("archive entry {:?} is not a plain relative path", entry_path),
+            ));
         }
+
+        // Symlinks/hardlinks can point outside the target directory, and
+        // this directory is served back out over HTTP, so refuse them
+        // outright rather than trying to validate their targets.
+        let entry_type = entry.header().entry_type();
+        if entry_type.is_symlink() || entry_type.is_hard_link() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("archive entry {:?} is a symlink or hard link, which is not allowed", entry_path),
+            ));
+        }
+
+        entry.unpack_in(target_dir)?;
     }
+    Ok(())
 }
 
-async fn extract_req_res_info(req: Request<Body>, next: Next<Body>) -> impl IntoResponse {
-    let (parts, req_body) = req.into_parts();
-    let uri = parts.uri.to_string();
-    let method = parts.method.to_string();
-    let req_body_bytes = hyper::body::to_bytes(req_body).await.unwrap();
-    let req_body = std::str::from_utf8(req_body_bytes.as_ref()).unwrap().to_string();
+/// Extracts `archive_path` into a fresh temp directory next to `static_dir`,
+/// then moves `static_dir` aside and renames the new directory into its
+/// place, restoring the original if that second rename fails. The swap is
+/// two renames, not one atomic step: between them `static_dir` does not exist.
+fn extract_and_swap(archive_path: &std::path::Path, static_dir: &std::path::Path) -> std::io::Result<()> {
+    let parent = static_dir.parent().unwrap_or_else(|| std::path::Path::new("."));
+    let extract_dir = tempfile::tempdir_in(parent)?;
+    extract_tar_gz(archive_path, extract_dir.path())?;
 
-    let mut access_log = AccessLog {
-        uri,
-        method,
-        req_body,
-        status_code: StatusCode::OK.as_u16()
+    let backup_dir = parent.join(format!(
+        ".{}.bak",
+        static_dir.file_name().and_then(|n| n.to_str()).unwrap_or("static-deploy")
+    ));
+    let _ = fs::remove_dir_all(&backup_dir);
+
+    fs::rename(static_dir, &backup_dir)?;
+    if let Err(err) = fs::rename(extract_dir.path(), static_dir) {
+        let _ = fs::rename(&backup_dir, static_dir);
+        return Err(err);
+    }
+    let _ = fs::remove_dir_all(&backup_dir);
+    Ok(())
+}
+
+/// `POST /_deploy` — accepts a gzipped tar stream and replaces the static
+/// root with its contents (see `extract_and_swap`). Guarded by a bearer token; the live
+/// directory is left untouched unless extraction fully succeeds.
+async fn deploy_handler(
+    State(app_setting): State<Arc<AppSetting>>,
+    headers: HeaderMap,
+    body: BodyStream,
+) -> impl IntoResponse {
+    let Some(configured_token) = app_setting.deploy_token.as_deref() else {
+        return empty_response(StatusCode::FORBIDDEN);
+    };
+
+    let authorized = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(|token| {
+            // Constant-time comparison: `==` short-circuits on the first
+            // mismatched byte, which leaks how much of the token a guess got
+            // right through response timing.
+            bool::from(token.as_bytes().ct_eq(configured_token.as_bytes()))
+        })
+        .unwrap_or(false);
+    if !authorized {
+        return empty_response(StatusCode::UNAUTHORIZED);
+    }
+
+    let archive_file = match tempfile::NamedTempFile::new() {
+        Ok(file) => file,
+        Err(_) => return internal_server_error(),
+    };
+    let archive_path = archive_file.path().to_path_buf();
+
+    let mut reader = StreamReader::new(
+        body.map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e)),
+    );
+    let mut archive_out = match tokio::fs::File::create(&archive_path).await {
+        Ok(file) => file,
+        Err(_) => return internal_server_error(),
     };
-    let req = Request::from_parts(parts, Body::from(req_body_bytes));
+    if tokio::io::copy(&mut reader, &mut archive_out).await.is_err() {
+        return internal_server_error();
+    }
+    drop(archive_out);
+
+    let static_dir = std::path::PathBuf::from(&app_setting.static_dir);
+    let result = tokio::task::spawn_blocking(move || extract_and_swap(&archive_path, &static_dir)).await;
+
+    match result {
+        Ok(Ok(())) => empty_response(StatusCode::OK),
+        _ => internal_server_error(),
+    }
+}
+
+/// Expands an access-log template against a completed request/response pair.
+fn render_log_format(
+    template: &str,
+    remote_addr: &str,
+    request_method: &str,
+    uri: &str,
+    status: &str,
+    http_user_agent: &str,
+    request_time: &str,
+    body_bytes_sent: &str,
+) -> String {
+    // Substituted values (uri, http_user_agent, ...) are attacker-controlled
+    // and may themselves contain literal "$token" text. Scan the template in
+    // a single pass instead of chaining `.replace()` calls, so a value
+    // substituted early can't be re-matched and overwritten by a later one.
+    let tokens: &[(&str, &str)] = &[
+        ("$request_method", request_method),
+        ("$http_user_agent", http_user_agent),
+        ("$body_bytes_sent", body_bytes_sent),
+        ("$remote_addr", remote_addr),
+        ("$request_time", request_time),
+        ("$status", status),
+        ("$uri", uri),
+    ];
+
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(dollar_pos) = rest.find('$') {
+        output.push_str(&rest[..dollar_pos]);
+        let candidate = &rest[dollar_pos..];
+        match tokens.iter().find(|(token, _)| candidate.starts_with(token)) {
+            Some((token, value)) => {
+                output.push_str(value);
+                rest = &candidate[token.len()..];
+            }
+            None => {
+                output.push('$');
+                rest = &candidate[1..];
+            }
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+async fn extract_req_res_info(
+    State(app_setting): State<Arc<AppSetting>>,
+    ConnectInfo(remote_addr): ConnectInfo<SocketAddr>,
+    req: Request<Body>,
+    next: Next<Body>,
+) -> impl IntoResponse {
+    if app_setting.log_format.is_empty() {
+        return next.run(req).await;
+    }
+
+    let start = std::time::Instant::now();
+    let uri = req.uri().to_string();
+    let method = req.method().to_string();
+    let user_agent = req
+        .headers()
+        .get(header::USER_AGENT)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("-")
+        .to_string();
+
     let res = next.run(req).await;
-    let status_code = res.status().clone();
-    access_log.status_code = status_code.as_u16();
+
+    let status = res.status().as_u16().to_string();
+    let body_bytes_sent = res
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("-")
+        .to_string();
+    let request_time = format!("{:.6}", start.elapsed().as_secs_f64());
+
     tracing::debug!(
-            "{}", serde_json::to_string(&access_log).unwrap()
+        "{}",
+        render_log_format(
+            &app_setting.log_format,
+            &remote_addr.to_string(),
+            &method,
+            &uri,
+            &status,
+            &user_agent,
+            &request_time,
+            &body_bytes_sent,
+        )
     );
     res
 }
@@ -106,21 +732,207 @@ async fn main() {
         )
         .init();
 
-    let app_setting = AppSetting::parse();
+    let mut app_setting = AppSetting::parse();
     let port = app_setting.port;
+    app_setting.static_root = fs::canonicalize(&app_setting.static_dir)
+        .expect("static_dir must point to an existing directory");
 
     let state = Arc::new(app_setting);
 
     let app = Router::new()
+        .route("/_deploy", post(deploy_handler))
         .route("/*path", get(static_path_handler))
-        .layer(middleware::from_fn(extract_req_res_info))
+        .layer(middleware::from_fn_with_state(state.clone(), extract_req_res_info))
         .with_state(state);
 
     let addr = SocketAddr::from(([127, 0, 0, 1], port));
     tracing::debug!("listening on {}", addr);
 
     axum::Server::bind(&addr)
-        .serve(app.into_make_service())
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
         .await
         .unwrap();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_file(path: &std::path::Path, contents: &str) {
+        fs::write(path, contents).unwrap();
+    }
+
+    #[test]
+    fn resolve_within_root_serves_a_plain_file() {
+        let root = tempfile::tempdir().unwrap();
+        write_file(&root.path().join("index.html"), "hi");
+        let canonical_root = fs::canonicalize(root.path()).unwrap();
+
+        match resolve_within_root(&canonical_root, "/index.html") {
+            Resolved::File(path) => assert_eq!(path, canonical_root.join("index.html")),
+            _ => panic!("expected a servable file"),
+        }
+    }
+
+    #[test]
+    fn resolve_within_root_rejects_percent_encoded_dot_dot() {
+        let root = tempfile::tempdir().unwrap();
+        write_file(&root.path().join("index.html"), "hi");
+        let canonical_root = fs::canonicalize(root.path()).unwrap();
+
+        // `/..%2f..%2fetc%2fpasswd` decodes to `/../../etc/passwd`.
+        match resolve_within_root(&canonical_root, "/..%2f..%2fetc%2fpasswd") {
+            Resolved::NotFound | Resolved::Forbidden => {}
+            _ => panic!("path traversal should not resolve to a servable path"),
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn resolve_within_root_rejects_a_symlink_that_escapes_root() {
+        let outside = tempfile::tempdir().unwrap();
+        write_file(&outside.path().join("secret.txt"), "top secret");
+
+        let root = tempfile::tempdir().unwrap();
+        let canonical_root = fs::canonicalize(root.path()).unwrap();
+        std::os::unix::fs::symlink(
+            outside.path().join("secret.txt"),
+            root.path().join("escape.txt"),
+        )
+        .unwrap();
+
+        match resolve_within_root(&canonical_root, "/escape.txt") {
+            Resolved::Forbidden => {}
+            _ => panic!("a symlink escaping the root should be forbidden"),
+        }
+    }
+
+    #[test]
+    fn parse_range_handles_suffix_and_open_ended_forms() {
+        assert!(matches!(parse_range("bytes=0-499", 1000), RangeResult::Satisfiable(0, 499)));
+        assert!(matches!(parse_range("bytes=500-", 1000), RangeResult::Satisfiable(500, 999)));
+        assert!(matches!(parse_range("bytes=-500", 1000), RangeResult::Satisfiable(500, 999)));
+    }
+
+    #[test]
+    fn parse_range_rejects_out_of_bounds_and_empty_suffix() {
+        assert!(matches!(parse_range("bytes=2000-3000", 1000), RangeResult::Unsatisfiable));
+        assert!(matches!(parse_range("bytes=-0", 1000), RangeResult::Unsatisfiable));
+    }
+
+    #[test]
+    fn parse_range_falls_back_to_full_on_unrecognised_syntax() {
+        assert!(matches!(parse_range("not-a-range", 1000), RangeResult::Full));
+        assert!(matches!(parse_range("bytes=abc-def", 1000), RangeResult::Full));
+    }
+
+    #[test]
+    fn parse_accept_encoding_prefers_higher_quality_and_drops_zero_quality() {
+        let accepted = parse_accept_encoding("br;q=0.1, gzip;q=0.9, identity;q=0");
+        assert_eq!(accepted, vec!["gzip".to_string(), "br".to_string()]);
+    }
+
+    #[test]
+    fn parse_accept_encoding_defaults_missing_quality_to_one() {
+        let accepted = parse_accept_encoding("gzip, br;q=0.5");
+        assert_eq!(accepted, vec!["gzip".to_string(), "br".to_string()]);
+    }
+
+    fn build_tar_gz(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (name, contents) in entries {
+            let mut header = tar::Header::new_gnu();
+            // Write the name bytes as given: `append_data` goes through
+            // `Header::set_path`, which rejects `..` and strips `./`.
+            header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append(&header, *contents).unwrap();
+        }
+        let tar_bytes = builder.into_inner().unwrap();
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        std::io::Write::write_all(&mut encoder, &tar_bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn extract_tar_gz_accepts_a_plain_relative_entry() {
+        let archive_dir = tempfile::tempdir().unwrap();
+        let archive_path = archive_dir.path().join("site.tar.gz");
+        fs::write(&archive_path, build_tar_gz(&[("index.html", b"hi")])).unwrap();
+
+        let target = tempfile::tempdir().unwrap();
+        extract_tar_gz(&archive_path, target.path()).unwrap();
+        assert_eq!(fs::read_to_string(target.path().join("index.html")).unwrap(), "hi");
+    }
+
+    #[test]
+    fn extract_tar_gz_accepts_gnu_style_dot_slash_prefixed_entries() {
+        // GNU tar (e.g. `tar czf site.tar.gz -C dist .`) prefixes every
+        // entry's name with `./`; these must still extract normally.
+        let archive_dir = tempfile::tempdir().unwrap();
+        let archive_path = archive_dir.path().join("site.tar.gz");
+        fs::write(&archive_path, build_tar_gz(&[("./index.html", b"hi")])).unwrap();
+
+        let target = tempfile::tempdir().unwrap();
+        extract_tar_gz(&archive_path, target.path()).unwrap();
+        assert_eq!(fs::read_to_string(target.path().join("index.html")).unwrap(), "hi");
+    }
+
+    #[test]
+    fn extract_tar_gz_rejects_a_parent_dir_escape() {
+        let archive_dir = tempfile::tempdir().unwrap();
+        let archive_path = archive_dir.path().join("site.tar.gz");
+        fs::write(&archive_path, build_tar_gz(&[("../escape.txt", b"evil")])).unwrap();
+
+        let target = tempfile::tempdir().unwrap();
+        assert!(extract_tar_gz(&archive_path, target.path()).is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn extract_tar_gz_rejects_a_symlink_entry() {
+        let archive_dir = tempfile::tempdir().unwrap();
+        let archive_path = archive_dir.path().join("site.tar.gz");
+
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_size(0);
+        header.set_mode(0o777);
+        header.set_link_name("/etc/passwd").unwrap();
+        builder.append_data(&mut header, "link", std::io::empty()).unwrap();
+        let tar_bytes = builder.into_inner().unwrap();
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        std::io::Write::write_all(&mut encoder, &tar_bytes).unwrap();
+        fs::write(&archive_path, encoder.finish().unwrap()).unwrap();
+
+        let target = tempfile::tempdir().unwrap();
+        assert!(extract_tar_gz(&archive_path, target.path()).is_err());
+    }
+
+    #[test]
+    fn render_log_format_substitutes_every_token() {
+        let rendered = render_log_format(
+            "$remote_addr \"$request_method $uri\" $status $body_bytes_sent \"$http_user_agent\" $request_time",
+            "127.0.0.1",
+            "GET",
+            "/index.html",
+            "200",
+            "curl/8.0",
+            "0.001234",
+            "42",
+        );
+        assert_eq!(rendered, "127.0.0.1 \"GET /index.html\" 200 42 \"curl/8.0\" 0.001234");
+    }
+
+    #[test]
+    fn render_log_format_does_not_let_a_substituted_value_be_rematched() {
+        // A value containing literal token-like text must not be re-scanned
+        // and overwritten by another token's substitution, as chained
+        // `.replace()` calls would do when `$uri` is replaced before `$status`.
+        let rendered = render_log_format("$uri $status", "127.0.0.1", "GET", "$status", "200", "-", "0.0", "-");
+        assert_eq!(rendered, "$status 200");
+    }
+}